@@ -0,0 +1,169 @@
+use std::collections::BTreeMap;
+use chrono::{Datelike, Duration, NaiveDate, NaiveTime, Weekday};
+use icalendar::{Calendar, CalendarComponent, CalendarDateTime, Component, DatePerhapsTime, EventLike};
+use crate::config::{AgendaConfig, Locale};
+
+fn weekday_name(weekday: Weekday, locale: Locale) -> &'static str {
+    match locale {
+        Locale::en => match weekday {
+            Weekday::Mon => "Monday",
+            Weekday::Tue => "Tuesday",
+            Weekday::Wed => "Wednesday",
+            Weekday::Thu => "Thursday",
+            Weekday::Fri => "Friday",
+            Weekday::Sat => "Saturday",
+            Weekday::Sun => "Sunday",
+        },
+        Locale::de => match weekday {
+            Weekday::Mon => "Montag",
+            Weekday::Tue => "Dienstag",
+            Weekday::Wed => "Mittwoch",
+            Weekday::Thu => "Donnerstag",
+            Weekday::Fri => "Freitag",
+            Weekday::Sat => "Samstag",
+            Weekday::Sun => "Sonntag",
+        },
+    }
+}
+
+fn month_name(month: u32, locale: Locale) -> &'static str {
+    const EN: [&str; 12] = [
+        "January", "February", "March", "April", "May", "June",
+        "July", "August", "September", "October", "November", "December",
+    ];
+    const DE: [&str; 12] = [
+        "Januar", "Februar", "März", "April", "Mai", "Juni",
+        "Juli", "August", "September", "Oktober", "November", "Dezember",
+    ];
+
+    let names = match locale {
+        Locale::en => &EN,
+        Locale::de => &DE,
+    };
+    names[month as usize - 1]
+}
+
+fn all_day_label(locale: Locale) -> &'static str {
+    match locale {
+        Locale::en => "All day",
+        Locale::de => "Ganztägig",
+    }
+}
+
+fn weekend_marker(locale: Locale) -> &'static str {
+    match locale {
+        Locale::en => " — weekend",
+        Locale::de => " — Wochenende",
+    }
+}
+
+fn no_events_label(locale: Locale) -> &'static str {
+    match locale {
+        Locale::en => "_No events_",
+        Locale::de => "_Keine Termine_",
+    }
+}
+
+fn to_date_and_time(value: DatePerhapsTime) -> (NaiveDate, Option<NaiveTime>) {
+    match value {
+        DatePerhapsTime::Date(date) => (date, None),
+        DatePerhapsTime::DateTime(CalendarDateTime::Utc(dt)) => (dt.date_naive(), Some(dt.time())),
+        DatePerhapsTime::DateTime(CalendarDateTime::Floating(dt)) => (dt.date(), Some(dt.time())),
+        DatePerhapsTime::DateTime(CalendarDateTime::WithTimezone { date_time, .. }) => {
+            (date_time.date(), Some(date_time.time()))
+        },
+    }
+}
+
+struct AgendaEntry {
+    start: Option<NaiveTime>,
+    end: Option<NaiveTime>,
+    summary: String,
+    location: Option<String>,
+}
+
+/// Renders `calendar`'s events, restricted to `[from, until]`, as a
+/// Markdown agenda grouped under per-day headings in chronological order.
+pub fn render(calendar: &Calendar, from: NaiveDate, until: NaiveDate, locale: Locale, config: &AgendaConfig) -> String {
+    let mut by_day: BTreeMap<NaiveDate, Vec<AgendaEntry>> = BTreeMap::new();
+
+    for component in &calendar.components {
+        let CalendarComponent::Event(event) = component else {
+            continue;
+        };
+
+        let (Some(start), Some(end)) = (event.get_start(), event.get_end()) else {
+            continue;
+        };
+
+        let (day, start_time) = to_date_and_time(start);
+        let (_, end_time) = to_date_and_time(end);
+
+        // Events that started before `from` but still overlap the window
+        // (multi-day events, or a window::TimeWindow::retain match via
+        // `end >= from`) are shown on the first visible day instead of
+        // under a heading the loop below never reaches.
+        let day = day.max(from);
+
+        by_day.entry(day).or_default().push(AgendaEntry {
+            start: start_time,
+            end: end_time,
+            summary: event.get_summary().unwrap_or("(no title)").to_string(),
+            location: event.get_location().map(str::to_string),
+        });
+    }
+
+    for entries in by_day.values_mut() {
+        entries.sort_by_key(|entry| entry.start);
+    }
+
+    let mut output = String::new();
+    let mut last_week = None;
+    let mut day = from;
+
+    while day <= until {
+        let has_entries = by_day.get(&day).is_some_and(|entries| !entries.is_empty());
+        if has_entries || config.print_empty_days {
+            let week = day.iso_week().week();
+            if config.week_separator && last_week.is_some_and(|last_week| last_week != week) {
+                output.push_str("---\n\n");
+            }
+            last_week = Some(week);
+
+            let weekend = matches!(day.weekday(), Weekday::Sat | Weekday::Sun)
+                .then(|| weekend_marker(locale))
+                .unwrap_or_default();
+            output.push_str(&format!(
+                "## {}, {} {} {}{weekend}\n\n",
+                weekday_name(day.weekday(), locale),
+                day.day(),
+                month_name(day.month(), locale),
+                day.year(),
+            ));
+
+            match by_day.get(&day) {
+                Some(entries) if !entries.is_empty() => {
+                    for entry in entries {
+                        let time = match (entry.start, entry.end) {
+                            (Some(start), Some(end)) => format!("{}–{}", start.format("%H:%M"), end.format("%H:%M")),
+                            _ => all_day_label(locale).to_string(),
+                        };
+                        let location = entry.location.as_deref()
+                            .map(|location| format!(" _({location})_"))
+                            .unwrap_or_default();
+                        output.push_str(&format!("- {time} **{}**{location}\n", entry.summary));
+                    }
+                    output.push('\n');
+                },
+                _ => {
+                    output.push_str(no_events_label(locale));
+                    output.push_str("\n\n");
+                },
+            }
+        }
+
+        day += Duration::days(1);
+    }
+
+    output
+}
@@ -0,0 +1,106 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use actix_web::web;
+use chrono::{DateTime, Duration, Utc};
+use icalendar::Calendar;
+use tracing::warn;
+use crate::build_calendar;
+use crate::config::{AppConfig, Locale};
+use crate::filter::EventFilter;
+
+fn etag_for(calendar: &Calendar) -> String {
+    let mut hasher = DefaultHasher::new();
+    calendar.to_string().hash(&mut hasher);
+    format!("\"{:x}\"", hasher.finish())
+}
+
+#[derive(Clone)]
+pub struct CacheEntry {
+    pub calendar: Calendar,
+    pub locale: Locale,
+    pub fetched_at: DateTime<Utc>,
+    pub etag: String,
+}
+
+async fn fetch(config: &AppConfig, filter: &EventFilter) -> anyhow::Result<CacheEntry> {
+    let (calendar, locale, _) = build_calendar(config, filter).await?;
+    Ok(CacheEntry { etag: etag_for(&calendar), fetched_at: Utc::now(), calendar, locale })
+}
+
+/// Last-known-good copy of the merged, filtered calendar plus when it was
+/// fetched, so a slow or unreachable TISS doesn't take the served
+/// calendar down with it.
+pub struct CalendarCache {
+    entry: Mutex<Option<CacheEntry>>,
+    /// Single-flight guard so a burst of requests against a just-expired
+    /// entry triggers at most one background refresh instead of one per
+    /// request.
+    refreshing: AtomicBool,
+}
+
+impl Default for CalendarCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CalendarCache {
+    pub fn new() -> Self {
+        Self { entry: Mutex::new(None), refreshing: AtomicBool::new(false) }
+    }
+
+    fn snapshot(&self) -> Option<CacheEntry> {
+        self.entry.lock().unwrap().clone()
+    }
+
+    fn store(&self, entry: CacheEntry) {
+        *self.entry.lock().unwrap() = Some(entry);
+    }
+
+    /// Claims the right to run the one background refresh in flight.
+    /// Returns `false` (and claims nothing) if a refresh is already running.
+    fn start_refresh(&self) -> bool {
+        self.refreshing.compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire).is_ok()
+    }
+
+    fn finish_refresh(&self) {
+        self.refreshing.store(false, Ordering::Release);
+    }
+}
+
+/// Returns the calendar to serve: fetches synchronously if nothing has
+/// ever been cached, serves the cached copy as-is while it's within the
+/// configured TTL, or serves the stale cached copy while kicking off a
+/// background refresh. Errors only propagate when there is no cached
+/// copy to fall back to.
+pub async fn get(
+    cache: web::Data<CalendarCache>,
+    config: web::Data<AppConfig>,
+    filter: web::Data<EventFilter>,
+) -> anyhow::Result<CacheEntry> {
+    let ttl = Duration::seconds(config.cache.ttl_seconds as i64);
+
+    match cache.snapshot() {
+        None => {
+            let entry = fetch(&config, &filter).await?;
+            cache.store(entry.clone());
+            Ok(entry)
+        },
+        Some(entry) if Utc::now() - entry.fetched_at < ttl => Ok(entry),
+        Some(stale) => {
+            if cache.start_refresh() {
+                let (cache, config, filter) = (cache.clone(), config.clone(), filter.clone());
+                actix_web::rt::spawn(async move {
+                    match fetch(&config, &filter).await {
+                        Ok(entry) => cache.store(entry),
+                        Err(err) => warn!(error = %err, "Failed to refresh calendar cache, serving stale copy"),
+                    }
+                    cache.finish_refresh();
+                });
+            }
+            Ok(stale)
+        },
+    }
+}
@@ -0,0 +1,405 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+use std::sync::{LazyLock, Mutex};
+use actix_web::{web, HttpResponse, Responder};
+use actix_web::http::StatusCode;
+use icalendar::{Calendar, CalendarComponent, Component, Event};
+use regex::Regex;
+use crate::cache::{self, CalendarCache};
+use crate::config::AppConfig;
+use crate::filter::EventFilter;
+
+const CALENDAR_COLLECTION_HREF: &str = "/caldav/calendar";
+const SYNC_TOKEN_PREFIX: &str = "tu-planner-sync-";
+/// Diff batches older than this are pruned; clients presenting a sync
+/// token older than what's retained just get a full resync instead.
+const MAX_RETAINED_DIFFS: usize = 500;
+
+#[derive(Debug, Clone)]
+enum UidChange {
+    Upserted(String),
+    Removed(String),
+}
+
+struct CalDavState {
+    token: u64,
+    hashes: HashMap<String, u64>,
+    diffs: BTreeMap<u64, Vec<UidChange>>,
+}
+
+/// Per-UID content hashes of the merged, filtered calendar across
+/// requests, so `sync-collection` REPORTs can diff against an older
+/// client-presented sync token instead of resending everything.
+pub struct CalDavStore {
+    inner: Mutex<CalDavState>,
+}
+
+impl Default for CalDavStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CalDavStore {
+    pub fn new() -> Self {
+        Self {
+            inner: Mutex::new(CalDavState {
+                token: 0,
+                hashes: HashMap::new(),
+                diffs: BTreeMap::new(),
+            }),
+        }
+    }
+
+    /// Records the current state of `calendar`, bumping the sync token
+    /// only when a UID was added, removed, or its content changed since
+    /// the last observation. Returns the (possibly unchanged) token.
+    fn observe(&self, calendar: &Calendar) -> u64 {
+        let mut state = self.inner.lock().unwrap();
+
+        let mut new_hashes = HashMap::new();
+        for component in &calendar.components {
+            if let CalendarComponent::Event(event) = component {
+                if let Some(uid) = event.get_uid() {
+                    new_hashes.insert(uid.to_string(), hash_event(event));
+                }
+            }
+        }
+
+        let mut changes = Vec::new();
+        for (uid, hash) in &new_hashes {
+            if state.hashes.get(uid) != Some(hash) {
+                changes.push(UidChange::Upserted(uid.clone()));
+            }
+        }
+        for uid in state.hashes.keys() {
+            if !new_hashes.contains_key(uid) {
+                changes.push(UidChange::Removed(uid.clone()));
+            }
+        }
+
+        if !changes.is_empty() {
+            state.token += 1;
+            state.diffs.insert(state.token, changes);
+            state.hashes = new_hashes;
+
+            while state.diffs.len() > MAX_RETAINED_DIFFS {
+                state.diffs.pop_first();
+            }
+        }
+
+        state.token
+    }
+
+    /// Changed/removed UIDs since `token`. `None` means `token` predates
+    /// the oldest retained diff (or is unknown) and a full resync is
+    /// required instead.
+    fn changes_since(&self, token: u64) -> Option<(u64, Vec<UidChange>)> {
+        let state = self.inner.lock().unwrap();
+
+        if token > state.token {
+            return None;
+        }
+        if let Some(&oldest) = state.diffs.keys().next() {
+            if token < oldest - 1 {
+                return None;
+            }
+        } else if token != state.token {
+            return None;
+        }
+
+        let mut merged: HashMap<String, UidChange> = HashMap::new();
+        for changes in state.diffs.range((token + 1)..).map(|(_, changes)| changes) {
+            for change in changes {
+                let uid = match change {
+                    UidChange::Upserted(uid) | UidChange::Removed(uid) => uid.clone(),
+                };
+                merged.insert(uid, change.clone());
+            }
+        }
+
+        Some((state.token, merged.into_values().collect()))
+    }
+}
+
+fn hash_event(event: &Event) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    event.to_string().hash(&mut hasher);
+    hasher.finish()
+}
+
+fn sync_token(token: u64) -> String {
+    format!("{SYNC_TOKEN_PREFIX}{token}")
+}
+
+fn parse_sync_token(body: &str) -> Option<u64> {
+    static TOKEN_RE: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r"(?s)<[A-Za-z0-9]*:?sync-token[^>]*>\s*([^<]*)\s*</[A-Za-z0-9]*:?sync-token>").unwrap()
+    });
+
+    TOKEN_RE.captures(body)?
+        .get(1)?
+        .as_str()
+        .trim()
+        .strip_prefix(SYNC_TOKEN_PREFIX)?
+        .parse()
+        .ok()
+}
+
+fn is_sync_collection(body: &str) -> bool {
+    body.contains("sync-collection")
+}
+
+fn event_href(uid: &str) -> String {
+    format!("{CALENDAR_COLLECTION_HREF}/{uid}.ics")
+}
+
+fn serialize_event(event: &Event) -> String {
+    let mut calendar = Calendar::new();
+    calendar.components.push(CalendarComponent::Event(event.clone()));
+    calendar.to_string()
+}
+
+/// `PROPFIND` on the calendar collection: exposes just enough WebDAV/CalDAV
+/// properties (`calendar-home-set`, `displayname`, `getctag`,
+/// `supported-calendar-component-set`) for a client to discover the
+/// collection and notice when it has changed.
+pub async fn propfind(
+    cache: web::Data<CalendarCache>,
+    config: web::Data<AppConfig>,
+    filter: web::Data<EventFilter>,
+    store: web::Data<CalDavStore>,
+) -> impl Responder {
+    let entry = match cache::get(cache, config, filter).await {
+        Ok(entry) => entry,
+        Err(err) => return HttpResponse::InternalServerError().body(err.to_string()),
+    };
+    let token = store.observe(&entry.calendar);
+
+    let body = format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<D:multistatus xmlns:D="DAV:" xmlns:C="urn:ietf:params:xml:ns:caldav" xmlns:CS="http://calendarserver.org/ns/">
+  <D:response>
+    <D:href>{CALENDAR_COLLECTION_HREF}</D:href>
+    <D:propstat>
+      <D:prop>
+        <D:displayname>tu-planner</D:displayname>
+        <D:calendar-home-set><D:href>{CALENDAR_COLLECTION_HREF}</D:href></D:calendar-home-set>
+        <C:supported-calendar-component-set><C:comp name="VEVENT"/></C:supported-calendar-component-set>
+        <CS:getctag>{token}</CS:getctag>
+      </D:prop>
+      <D:status>HTTP/1.1 200 OK</D:status>
+    </D:propstat>
+  </D:response>
+</D:multistatus>"#
+    );
+
+    HttpResponse::build(StatusCode::MULTI_STATUS)
+        .content_type("application/xml; charset=utf-8")
+        .body(body)
+}
+
+fn changed_response(href: &str) -> String {
+    format!(
+        r#"  <D:response>
+    <D:href>{href}</D:href>
+    <D:propstat>
+      <D:prop><D:getetag/></D:prop>
+      <D:status>HTTP/1.1 200 OK</D:status>
+    </D:propstat>
+  </D:response>
+"#
+    )
+}
+
+fn removed_response(href: &str) -> String {
+    format!(
+        r#"  <D:response>
+    <D:href>{href}</D:href>
+    <D:status>HTTP/1.1 404 Not Found</D:status>
+  </D:response>
+"#
+    )
+}
+
+fn sync_collection_response(token: u64, changes: &[UidChange]) -> HttpResponse {
+    let mut responses = String::new();
+    for change in changes {
+        match change {
+            UidChange::Upserted(uid) => responses.push_str(&changed_response(&event_href(uid))),
+            UidChange::Removed(uid) => responses.push_str(&removed_response(&event_href(uid))),
+        }
+    }
+
+    let body = format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<D:multistatus xmlns:D="DAV:">
+{responses}  <D:sync-token>{}</D:sync-token>
+</D:multistatus>"#,
+        sync_token(token)
+    );
+
+    HttpResponse::build(StatusCode::MULTI_STATUS)
+        .content_type("application/xml; charset=utf-8")
+        .body(body)
+}
+
+fn calendar_query_response(calendar: &Calendar) -> HttpResponse {
+    let mut responses = String::new();
+    for component in &calendar.components {
+        let CalendarComponent::Event(event) = component else {
+            continue;
+        };
+        let Some(uid) = event.get_uid() else {
+            continue;
+        };
+
+        responses.push_str(&format!(
+            r#"  <D:response>
+    <D:href>{}</D:href>
+    <D:propstat>
+      <D:prop><C:calendar-data><![CDATA[{}]]></C:calendar-data></D:prop>
+      <D:status>HTTP/1.1 200 OK</D:status>
+    </D:propstat>
+  </D:response>
+"#,
+            event_href(uid),
+            serialize_event(event),
+        ));
+    }
+
+    let body = format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<D:multistatus xmlns:D="DAV:" xmlns:C="urn:ietf:params:xml:ns:caldav">
+{responses}</D:multistatus>"#
+    );
+
+    HttpResponse::build(StatusCode::MULTI_STATUS)
+        .content_type("application/xml; charset=utf-8")
+        .body(body)
+}
+
+/// `REPORT` on the calendar collection: `sync-collection` returns only
+/// the hrefs that changed or were removed since the client's sync token
+/// (or a full listing if the token is foreign or too old); any other
+/// report (`calendar-query`) falls back to listing every current event.
+pub async fn report(
+    cache: web::Data<CalendarCache>,
+    config: web::Data<AppConfig>,
+    filter: web::Data<EventFilter>,
+    store: web::Data<CalDavStore>,
+    body: web::Bytes,
+) -> impl Responder {
+    let body = String::from_utf8_lossy(&body).into_owned();
+    let entry = match cache::get(cache, config, filter).await {
+        Ok(entry) => entry,
+        Err(err) => return HttpResponse::InternalServerError().body(err.to_string()),
+    };
+    let calendar = entry.calendar;
+    let current_token = store.observe(&calendar);
+
+    if is_sync_collection(&body) {
+        let changes = parse_sync_token(&body).and_then(|requested| store.changes_since(requested));
+        return match changes {
+            Some((token, changes)) => sync_collection_response(token, &changes),
+            None => {
+                let full_resync: Vec<_> = calendar.components.iter()
+                    .filter_map(|component| match component {
+                        CalendarComponent::Event(event) => event.get_uid().map(|uid| UidChange::Upserted(uid.to_string())),
+                        _ => None,
+                    })
+                    .collect();
+                sync_collection_response(current_token, &full_resync)
+            },
+        };
+    }
+
+    calendar_query_response(&calendar)
+}
+
+#[cfg(test)]
+mod tests {
+    use icalendar::Event;
+    use super::*;
+
+    fn event(uid: &str, summary: &str) -> Event {
+        Event::new().uid(uid).summary(summary).done()
+    }
+
+    fn calendar(events: Vec<Event>) -> Calendar {
+        let mut calendar = Calendar::new();
+        calendar.components = events.into_iter().map(CalendarComponent::Event).collect();
+        calendar
+    }
+
+    #[test]
+    fn first_observation_bumps_the_token_from_zero() {
+        let store = CalDavStore::new();
+        assert_eq!(store.observe(&calendar(vec![event("a", "Foo")])), 1);
+    }
+
+    #[test]
+    fn observing_the_same_calendar_again_does_not_bump_the_token() {
+        let store = CalDavStore::new();
+        let cal = calendar(vec![event("a", "Foo")]);
+        let first = store.observe(&cal);
+        let second = store.observe(&cal);
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn changing_an_event_bumps_the_token() {
+        let store = CalDavStore::new();
+        store.observe(&calendar(vec![event("a", "Foo")]));
+        assert_eq!(store.observe(&calendar(vec![event("a", "Bar")])), 2);
+    }
+
+    #[test]
+    fn changes_since_the_current_token_is_empty() {
+        let store = CalDavStore::new();
+        let token = store.observe(&calendar(vec![event("a", "Foo")]));
+        let (returned_token, changes) = store.changes_since(token).unwrap();
+        assert_eq!(returned_token, token);
+        assert!(changes.is_empty());
+    }
+
+    #[test]
+    fn changes_since_an_older_token_reports_the_update() {
+        let store = CalDavStore::new();
+        let first = store.observe(&calendar(vec![event("a", "Foo")]));
+        store.observe(&calendar(vec![event("a", "Bar")]));
+
+        let (_, changes) = store.changes_since(first).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert!(matches!(&changes[0], UidChange::Upserted(uid) if uid == "a"));
+    }
+
+    #[test]
+    fn changes_since_an_unknown_future_token_requires_a_full_resync() {
+        let store = CalDavStore::new();
+        let token = store.observe(&calendar(vec![event("a", "Foo")]));
+        assert!(store.changes_since(token + 1).is_none());
+    }
+
+    #[test]
+    fn removed_events_are_reported_as_removed() {
+        let store = CalDavStore::new();
+        let first = store.observe(&calendar(vec![event("a", "Foo")]));
+        store.observe(&calendar(vec![]));
+
+        let (_, changes) = store.changes_since(first).unwrap();
+        assert!(matches!(&changes[0], UidChange::Removed(uid) if uid == "a"));
+    }
+
+    #[test]
+    fn diffs_beyond_the_retention_cap_are_pruned() {
+        let store = CalDavStore::new();
+        store.observe(&calendar(vec![event("a", "v0")]));
+        for i in 0..MAX_RETAINED_DIFFS + 5 {
+            store.observe(&calendar(vec![event("a", &format!("v{i}"))]));
+        }
+
+        assert!(store.changes_since(1).is_none());
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::env::current_dir;
 use std::fmt::Display;
 use anyhow::{anyhow, Context};
@@ -68,14 +69,18 @@ pub enum TissConfig {
         /// The token used for auth
         token: Uuid,
     },
-    /// A token link from TISS
-    Link(Url),
+    /// A token link from TISS, under a named field so it can still be
+    /// reached through `#[serde(flatten)]` (e.g. from [`TissSource::Full`])
+    /// alongside sibling fields like `timezone`
+    Link {
+        link: Url,
+    },
 }
 
 impl TissConfig {
     pub fn link(&self) -> Url {
         match self {
-            Self::Link(link) => link.clone(),
+            Self::Link { link } => link.clone(),
             Self::Components { endpoint, locale, token } => {
                 let mut link = endpoint.clone();
                 link.query_pairs_mut()
@@ -88,7 +93,7 @@ impl TissConfig {
 
     pub fn locale(&self) -> anyhow::Result<Locale> {
         match self {
-            Self::Link(link) => {
+            Self::Link { link } => {
                 link.query_pairs()
                     .find(|(key, _)| *key == "locale")
                     .ok_or(anyhow!("Could not find locale query parameter in tiss token link"))?
@@ -113,9 +118,188 @@ impl Default for ServiceConfig {
     }
 }
 
+/// A single calendar source: either a bare TISS token link, or a link plus
+/// optional per-source options like `timezone`.
+#[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum TissSource {
+    /// Bare TISS token link, e.g. `tiss.personal = "https://tiss.../..."`
+    Link(Url),
+    /// A source plus per-source options
+    Full {
+        #[serde(flatten)]
+        tiss: TissConfig,
+        /// IANA timezone (e.g. `Europe/Vienna`) to normalize this source's
+        /// event times into before merging, overriding whatever zone (or
+        /// lack thereof) TISS emitted them in
+        #[serde(default)]
+        timezone: Option<String>,
+    },
+}
+
+impl TissSource {
+    pub fn tiss(&self) -> TissConfig {
+        match self {
+            Self::Link(link) => TissConfig::Link { link: link.clone() },
+            Self::Full { tiss, .. } => tiss.clone(),
+        }
+    }
+
+    pub fn timezone(&self) -> Option<&str> {
+        match self {
+            Self::Link(_) => None,
+            Self::Full { timezone, .. } => timezone.as_deref(),
+        }
+    }
+}
+
 #[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
 pub struct AppConfig {
-    pub tiss: TissConfig,
+    /// TISS sources to fetch and merge into the served calendar, keyed by a
+    /// user-chosen name (e.g. `[tiss.personal]`, `[tiss.secretary]`)
+    pub tiss: HashMap<String, TissSource>,
+    /// Locale advertised via `Content-Language` on the served calendar and
+    /// agenda. Set explicitly rather than inferred from any one source, since
+    /// sources can disagree and map iteration order isn't meaningful.
+    pub locale: Locale,
+    #[serde(default)]
+    pub filter: FilterConfig,
+    #[serde(default)]
+    pub window: WindowConfig,
+    #[serde(default)]
+    pub agenda: AgendaConfig,
+    #[serde(default)]
+    pub cache: CacheConfig,
+}
+
+/// Upstream fetch caching options for the served calendar
+#[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub struct CacheConfig {
+    /// How long a fetched-and-filtered calendar is served before a
+    /// background refresh is kicked off
+    #[serde(default = "CacheConfig::default_ttl_seconds")]
+    pub ttl_seconds: u64,
+}
+
+impl CacheConfig {
+    fn default_ttl_seconds() -> u64 {
+        300
+    }
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self { ttl_seconds: Self::default_ttl_seconds() }
+    }
+}
+
+/// Rendering options for the Markdown `/agenda` endpoint
+#[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub struct AgendaConfig {
+    /// Whether days without events still get a heading
+    #[serde(default)]
+    pub print_empty_days: bool,
+    /// Whether to print a separator between ISO weeks
+    #[serde(default = "AgendaConfig::default_week_separator")]
+    pub week_separator: bool,
+}
+
+impl AgendaConfig {
+    fn default_week_separator() -> bool {
+        true
+    }
+}
+
+impl Default for AgendaConfig {
+    fn default() -> Self {
+        Self {
+            print_empty_days: false,
+            week_separator: Self::default_week_separator(),
+        }
+    }
+}
+
+/// How far around "now" the served calendar extends
+#[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub struct WindowConfig {
+    /// Days into the future an event may start and still be kept
+    #[serde(default = "WindowConfig::default_up_days")]
+    pub up_days: i64,
+    /// Days into the past an event may have ended and still be kept
+    #[serde(default = "WindowConfig::default_down_days")]
+    pub down_days: i64,
+}
+
+impl WindowConfig {
+    fn default_up_days() -> i64 {
+        30
+    }
+
+    fn default_down_days() -> i64 {
+        7
+    }
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        Self {
+            up_days: Self::default_up_days(),
+            down_days: Self::default_down_days(),
+        }
+    }
+}
+
+/// Field of a `VEVENT` a [`FilterRule`] matches against
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterTarget {
+    Summary,
+    Description,
+    Location,
+    Categories,
+}
+
+/// What to do with an event matched by a [`FilterRule`]
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterAction {
+    Include,
+    Exclude,
+}
+
+/// A single event filter rule: if `pattern` matches the event's `target`
+/// field, `action` decides whether the event is kept or dropped
+#[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub struct FilterRule {
+    pub target: FilterTarget,
+    pub pattern: String,
+    pub action: FilterAction,
+}
+
+/// Ordered list of event filter rules. Rules are tried in order and the
+/// first match decides the event's fate; defaults to excluding events
+/// whose description contains `SPK`, matching the previous hardcoded
+/// behavior.
+#[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub struct FilterConfig {
+    #[serde(default = "FilterConfig::default_rules")]
+    pub rules: Vec<FilterRule>,
+}
+
+impl FilterConfig {
+    fn default_rules() -> Vec<FilterRule> {
+        vec![FilterRule {
+            target: FilterTarget::Description,
+            pattern: "\\WSPK\\W".to_string(),
+            action: FilterAction::Exclude,
+        }]
+    }
+}
+
+impl Default for FilterConfig {
+    fn default() -> Self {
+        Self { rules: Self::default_rules() }
+    }
 }
 
 /// TU Planner configuration
@@ -159,3 +343,51 @@ impl Config {
         Ok(config)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use figment::Figment;
+    use figment::providers::{Format, Toml};
+    use super::TissSource;
+
+    fn source(toml: &str) -> TissSource {
+        Figment::new()
+            .merge(Toml::string(toml))
+            .extract_inner("source")
+            .expect("source should deserialize")
+    }
+
+    #[test]
+    fn bare_link_shorthand_deserializes() {
+        let source = source(r#"source = "https://tiss.tuwien.ac.at/events/rest/calendar/personal?token=abc&locale=en""#);
+        assert!(matches!(source, TissSource::Link(_)));
+        assert_eq!(source.timezone(), None);
+    }
+
+    #[test]
+    fn link_table_with_timezone_deserializes() {
+        let source = source(
+            r#"
+            source.link = "https://tiss.tuwien.ac.at/events/rest/calendar/personal?token=abc&locale=en"
+            source.timezone = "Europe/Vienna"
+            "#,
+        );
+        assert_eq!(source.timezone(), Some("Europe/Vienna"));
+        assert_eq!(
+            source.tiss().link().as_str(),
+            "https://tiss.tuwien.ac.at/events/rest/calendar/personal?token=abc&locale=en",
+        );
+    }
+
+    #[test]
+    fn components_table_with_timezone_deserializes() {
+        let source = source(
+            r#"
+            source.locale = "en"
+            source.token = "00000000-0000-0000-0000-000000000000"
+            source.timezone = "Europe/Vienna"
+            "#,
+        );
+        assert_eq!(source.timezone(), Some("Europe/Vienna"));
+    }
+}
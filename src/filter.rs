@@ -0,0 +1,99 @@
+use anyhow::Context;
+use icalendar::{CalendarComponent, Component, Event};
+use regex::Regex;
+use crate::config::{FilterAction, FilterConfig, FilterRule, FilterTarget};
+
+/// A [`crate::config::FilterRule`] with its pattern compiled once at load time
+#[derive(Clone)]
+struct CompiledRule {
+    target: FilterTarget,
+    regex: Regex,
+    action: FilterAction,
+}
+
+/// Compiled, ready-to-apply form of [`FilterConfig`]
+#[derive(Clone)]
+pub struct EventFilter {
+    rules: Vec<CompiledRule>,
+}
+
+impl EventFilter {
+    /// Compiles every rule's pattern once so `retain` only ever matches
+    /// against an already-compiled [`Regex`]
+    pub fn compile(config: &FilterConfig) -> anyhow::Result<Self> {
+        let rules = config.rules.iter()
+            .map(|rule| Ok(CompiledRule {
+                target: rule.target,
+                regex: Regex::new(&rule.pattern)
+                    .with_context(|| format!("Invalid filter pattern {:?}", rule.pattern))?,
+                action: rule.action,
+            }))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        Ok(Self { rules })
+    }
+
+    /// Whether `component` should be kept, per the first rule that matches
+    /// it. Non-event components and events matched by no rule are kept.
+    pub fn retain(&self, component: &CalendarComponent) -> bool {
+        let CalendarComponent::Event(event) = component else {
+            return true;
+        };
+
+        for rule in &self.rules {
+            let value = match rule.target {
+                FilterTarget::Summary => event.get_summary(),
+                FilterTarget::Description => event.get_description(),
+                FilterTarget::Location => event.get_location(),
+                FilterTarget::Categories => event.property_value("CATEGORIES"),
+            };
+
+            if value.is_some_and(|value| rule.regex.is_match(value)) {
+                return match rule.action {
+                    FilterAction::Include => true,
+                    FilterAction::Exclude => false,
+                };
+            }
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(rules: Vec<FilterRule>) -> EventFilter {
+        EventFilter::compile(&FilterConfig { rules }).unwrap()
+    }
+
+    fn rule(target: FilterTarget, pattern: &str, action: FilterAction) -> FilterRule {
+        FilterRule { target, pattern: pattern.to_string(), action }
+    }
+
+    fn event_with_description(description: &str) -> CalendarComponent {
+        CalendarComponent::Event(Event::new().description(description).done())
+    }
+
+    #[test]
+    fn matching_exclude_rule_drops_the_event() {
+        let filter = filter(vec![rule(FilterTarget::Description, r"\WSPK\W", FilterAction::Exclude)]);
+        assert!(!filter.retain(&event_with_description("foo SPK bar")));
+    }
+
+    #[test]
+    fn non_matching_event_is_kept_by_default() {
+        let filter = filter(vec![rule(FilterTarget::Description, r"\WSPK\W", FilterAction::Exclude)]);
+        assert!(filter.retain(&event_with_description("unrelated lecture")));
+    }
+
+    #[test]
+    fn first_matching_rule_wins_over_later_ones() {
+        let filter = filter(vec![
+            rule(FilterTarget::Description, r"SPK", FilterAction::Include),
+            rule(FilterTarget::Description, r"SPK", FilterAction::Exclude),
+        ]);
+        assert!(filter.retain(&event_with_description("SPK")));
+    }
+}
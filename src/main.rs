@@ -1,15 +1,27 @@
+mod agenda;
+mod cache;
+mod caldav;
 mod config;
+mod filter;
+mod timezone;
+mod window;
 
+use std::collections::HashMap;
 use std::sync::LazyLock;
 use actix_web::{web, App, HttpResponse, HttpServer, Responder};
-use actix_web::http::header;
+use actix_web::http::{header, Method};
 use actix_web::http::header::{DispositionParam, DispositionType, QualityItem};
-use anyhow::Context;
-use icalendar::{Calendar, CalendarComponent, Component};
-use regex::Regex;
+use anyhow::{anyhow, Context};
+use chrono::{DateTime, TimeZone, Utc};
+use futures::future;
+use icalendar::{Calendar, CalendarDateTime, CalendarComponent, Component, DatePerhapsTime, Event};
 use tracing::{info, Level};
 use tracing_subscriber::{EnvFilter, FmtSubscriber};
-use crate::config::{AppConfig, Config, Locale};
+use crate::cache::CalendarCache;
+use crate::caldav::CalDavStore;
+use crate::config::{AppConfig, Config, Locale, TissSource};
+use crate::filter::EventFilter;
+use crate::window::TimeWindow;
 
 const APP_NAME: &str = "tu-planner";
 const APP_ENV_NAME: LazyLock<String> = LazyLock::new(|| {
@@ -24,9 +36,7 @@ const APP_ENV_NAME: LazyLock<String> = LazyLock::new(|| {
         .collect()
 });
 
-const SPK_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new("\\WSPK\\W").unwrap());
-
-fn calendar_response(calendar: Calendar, locale: Locale) -> HttpResponse {
+fn calendar_response(body: String, locale: Locale, etag: &str, last_modified: DateTime<Utc>) -> HttpResponse {
     let filename = "personal.ics".to_string();
     HttpResponse::Ok()
         .content_type("text/calendar")
@@ -35,29 +45,133 @@ fn calendar_response(calendar: Calendar, locale: Locale) -> HttpResponse {
             parameters: vec![DispositionParam::Filename(filename)],
         })
         .insert_header(header::ContentLanguage(vec![QualityItem::max(locale.into())]))
-        .body(calendar.to_string())
+        .insert_header((header::ETAG, etag))
+        .insert_header(header::LastModified(std::time::SystemTime::from(last_modified).into()))
+        .body(body)
+}
+
+async fn fetch_calendar(source: &TissSource, filter: &EventFilter) -> anyhow::Result<Calendar> {
+    let link = source.tiss().link();
+    let response = reqwest::get(link.clone()).await
+        .with_context(|| format!("Failed to fetch {link}"))?;
+    let body = response.text().await.context("Failed to read TISS response body")?;
+    let mut calendar: Calendar = body.parse().map_err(|err| anyhow!("Failed to parse TISS calendar: {err}"))?;
+
+    calendar.components.retain(|component| filter.retain(component));
+
+    if let Some(tz) = source.timezone() {
+        calendar = timezone::normalize(calendar, tz)?;
+    }
+
+    Ok(calendar)
 }
 
-async fn calendar(config: web::Data<AppConfig>) -> impl Responder {
-    let tiss_link = config.tiss.link();
-    let response = reqwest::get(tiss_link).await.unwrap();
-    let calendar = response.text().await.unwrap();
-    let mut calendar: Calendar = calendar.parse().unwrap();
-
-    calendar.components.retain(|component| {
-        match component {
-            CalendarComponent::Event(event) => {
-                if let Some(description) = event.get_description() {
-                    !SPK_REGEX.is_match(description)
-                } else {
-                    true
-                }
+/// The point in time a `VEVENT` was last touched upstream, used to decide
+/// which copy of a duplicated `UID` to keep when merging sources.
+fn event_recency(event: &Event) -> Option<DateTime<Utc>> {
+    event.get_last_modified()
+        .or_else(|| match event.get_timestamp() {
+            Some(DatePerhapsTime::DateTime(CalendarDateTime::Utc(dt))) => Some(dt),
+            Some(DatePerhapsTime::DateTime(CalendarDateTime::WithTimezone { date_time, .. })) => {
+                Some(Utc.from_utc_datetime(&date_time))
             },
-            _ => true,
+            Some(DatePerhapsTime::DateTime(CalendarDateTime::Floating(date_time))) => {
+                Some(Utc.from_utc_datetime(&date_time))
+            },
+            _ => None,
+        })
+}
+
+/// Merges several fetched calendars into one, deduplicating `VEVENT`s by
+/// `UID` and keeping the one with the most recent `DTSTAMP`/`LAST-MODIFIED`.
+fn merge_calendars(calendars: Vec<Calendar>) -> Calendar {
+    let mut events_by_uid: HashMap<String, (Event, Option<DateTime<Utc>>)> = HashMap::new();
+    let mut other_components = Vec::new();
+
+    for calendar in calendars {
+        for component in calendar.components {
+            match component {
+                CalendarComponent::Event(event) => {
+                    let Some(uid) = event.get_uid().map(str::to_string) else {
+                        other_components.push(CalendarComponent::Event(event));
+                        continue;
+                    };
+
+                    let recency = event_recency(&event);
+                    match events_by_uid.get(&uid) {
+                        Some((_, kept_recency)) if *kept_recency >= recency => {},
+                        _ => {
+                            events_by_uid.insert(uid, (event, recency));
+                        },
+                    }
+                },
+                other => other_components.push(other),
+            }
         }
-    });
-    
-    calendar_response(calendar, config.tiss.locale().unwrap())
+    }
+
+    let mut merged = Calendar::new();
+    merged.components = other_components;
+    merged.components.extend(
+        events_by_uid.into_values().map(|(event, _)| CalendarComponent::Event(event))
+    );
+    merged
+}
+
+pub(crate) async fn build_calendar(config: &AppConfig, filter: &EventFilter) -> anyhow::Result<(Calendar, Locale, TimeWindow)> {
+    let mut sources: Vec<_> = config.tiss.iter().collect();
+    sources.sort_by_key(|(name, _)| (*name).clone());
+
+    let locale = config.locale;
+
+    let calendars = future::join_all(
+        sources.iter().map(|(_, source)| fetch_calendar(source, filter))
+    ).await.into_iter().collect::<anyhow::Result<Vec<_>>>()?;
+
+    let mut calendar = merge_calendars(calendars);
+    let window = TimeWindow::new(Utc::now(), &config.window);
+    calendar.components.retain(|component| window.retain(component));
+
+    Ok((calendar, locale, window))
+}
+
+async fn calendar(
+    cache: web::Data<CalendarCache>,
+    config: web::Data<AppConfig>,
+    filter: web::Data<EventFilter>,
+    request: actix_web::HttpRequest,
+) -> impl Responder {
+    let entry = match cache::get(cache, config, filter).await {
+        Ok(entry) => entry,
+        Err(err) => return HttpResponse::ServiceUnavailable().body(err.to_string()),
+    };
+
+    let not_modified = request.headers().get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value == entry.etag);
+    if not_modified {
+        return HttpResponse::NotModified().finish();
+    }
+
+    calendar_response(timezone::render(&entry.calendar, entry.fetched_at), entry.locale, &entry.etag, entry.fetched_at)
+}
+
+async fn agenda(
+    cache: web::Data<CalendarCache>,
+    config: web::Data<AppConfig>,
+    filter: web::Data<EventFilter>,
+) -> impl Responder {
+    let entry = match cache::get(cache, config.clone(), filter).await {
+        Ok(entry) => entry,
+        Err(err) => return HttpResponse::ServiceUnavailable().body(err.to_string()),
+    };
+    let window = TimeWindow::new(entry.fetched_at, &config.window);
+    let body = agenda::render(&entry.calendar, window.from_date(), window.until_date(), entry.locale, &config.agenda);
+
+    HttpResponse::Ok()
+        .content_type("text/markdown")
+        .insert_header(header::ContentLanguage(vec![QualityItem::max(entry.locale.into())]))
+        .body(body)
 }
 
 #[actix_web::main]
@@ -72,12 +186,24 @@ async fn main() -> anyhow::Result<()> {
 
     let Config { app: app_config, service: service_config } = Config::load().context("Failed to load config")?;
 
+    let event_filter = web::Data::new(
+        EventFilter::compile(&app_config.filter).context("Failed to compile filter rules")?
+    );
+    let caldav_store = web::Data::new(CalDavStore::new());
+    let calendar_cache = web::Data::new(CalendarCache::new());
+
     info!("actix-web {APP_NAME}: listening on {}", service_config.bind);
 
     HttpServer::new(move || {
         let test = App::new()
             .route("/", web::get().to(calendar))
-            .app_data(web::Data::new(app_config.clone()));
+            .route("/agenda", web::get().to(agenda))
+            .route("/caldav/calendar", web::method(Method::from_bytes(b"PROPFIND").unwrap()).to(caldav::propfind))
+            .route("/caldav/calendar", web::method(Method::from_bytes(b"REPORT").unwrap()).to(caldav::report))
+            .app_data(web::Data::new(app_config.clone()))
+            .app_data(event_filter.clone())
+            .app_data(caldav_store.clone())
+            .app_data(calendar_cache.clone());
         test
     })
         .bind(service_config.bind)?
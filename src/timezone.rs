@@ -0,0 +1,224 @@
+use std::collections::HashMap;
+use anyhow::anyhow;
+use chrono::{DateTime, Duration, NaiveDateTime, Offset, TimeZone, Utc};
+use chrono_tz::Tz;
+use icalendar::{Calendar, CalendarComponent, CalendarDateTime, Component, DatePerhapsTime, EventLike};
+
+fn convert(value: DatePerhapsTime, tz: Tz) -> DatePerhapsTime {
+    match value {
+        DatePerhapsTime::Date(date) => DatePerhapsTime::Date(date),
+        DatePerhapsTime::DateTime(CalendarDateTime::Utc(dt)) => {
+            DatePerhapsTime::DateTime(CalendarDateTime::WithTimezone {
+                date_time: dt.with_timezone(&tz).naive_local(),
+                tzid: tz.name().to_string(),
+            })
+        },
+        DatePerhapsTime::DateTime(CalendarDateTime::Floating(date_time)) => {
+            DatePerhapsTime::DateTime(CalendarDateTime::WithTimezone { date_time, tzid: tz.name().to_string() })
+        },
+        DatePerhapsTime::DateTime(CalendarDateTime::WithTimezone { date_time, tzid }) => {
+            let date_time = tzid.parse::<Tz>().ok()
+                .and_then(|source_tz| source_tz.from_local_datetime(&date_time).single())
+                .map(|dt| dt.with_timezone(&tz).naive_local())
+                .unwrap_or(date_time);
+            DatePerhapsTime::DateTime(CalendarDateTime::WithTimezone { date_time, tzid: tz.name().to_string() })
+        },
+    }
+}
+
+/// Converts every `VEVENT`'s `DTSTART`/`DTEND` in `calendar` into `tz_name`,
+/// rewriting floating and foreign-timezone times consistently.
+pub fn normalize(mut calendar: Calendar, tz_name: &str) -> anyhow::Result<Calendar> {
+    let tz: Tz = tz_name.parse().map_err(|_| anyhow!("Unknown IANA timezone {tz_name:?}"))?;
+
+    for component in &mut calendar.components {
+        if let CalendarComponent::Event(event) = component {
+            if let Some(start) = event.get_start() {
+                event.starts(convert(start, tz));
+            }
+            if let Some(end) = event.get_end() {
+                event.ends(convert(end, tz));
+            }
+        }
+    }
+
+    Ok(calendar)
+}
+
+fn offset_seconds(tz: Tz, instant: DateTime<Utc>) -> i32 {
+    tz.offset_from_utc_datetime(&instant.naive_utc()).fix().local_minus_utc()
+}
+
+fn format_offset(seconds: i32) -> String {
+    chrono::FixedOffset::east_opt(seconds).unwrap()
+        .to_string()
+        .replace(':', "")
+}
+
+/// Binary-searches `(lo, hi]`, known to straddle exactly one offset change
+/// away from `offset_at_lo`, down to minute precision for the transition
+/// instant.
+fn bisect_transition(tz: Tz, mut lo: DateTime<Utc>, mut hi: DateTime<Utc>, offset_at_lo: i32) -> DateTime<Utc> {
+    while hi - lo > Duration::minutes(1) {
+        let mid = lo + (hi - lo) / 2;
+        if offset_seconds(tz, mid) == offset_at_lo {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    hi
+}
+
+/// Every offset change `tz` undergoes between `from` and `until`, found by
+/// daily sampling (cheap, and DST switches are never more frequent than
+/// that) followed by a bisection to pinpoint the exact instant.
+fn find_transitions(tz: Tz, from: DateTime<Utc>, until: DateTime<Utc>) -> Vec<(DateTime<Utc>, i32, i32)> {
+    let mut transitions = Vec::new();
+    let mut day = from;
+    let mut prev_offset = offset_seconds(tz, day);
+
+    while day <= until {
+        let next_day = day + Duration::days(1);
+        let offset = offset_seconds(tz, next_day);
+        if offset != prev_offset {
+            transitions.push((bisect_transition(tz, day, next_day, prev_offset), prev_offset, offset));
+        }
+        prev_offset = offset;
+        day = next_day;
+    }
+
+    transitions
+}
+
+/// A `VTIMEZONE` with real `STANDARD`/`DAYLIGHT` sub-components for every
+/// offset transition `tz` undergoes within `instants` (plus a margin), so
+/// events on either side of a DST switch resolve to the correct offset
+/// instead of whatever offset happened to be in effect at `now`. Falls
+/// back to a single fixed-offset `STANDARD` block if the zone doesn't
+/// transition in that range.
+fn vtimezone_block(tz: Tz, instants: &[DateTime<Utc>], now: DateTime<Utc>) -> String {
+    let margin = Duration::days(2);
+    let from = instants.iter().min().copied().unwrap_or(now) - margin;
+    let until = instants.iter().max().copied().unwrap_or(now) + margin;
+
+    let transitions = find_transitions(tz, from, until);
+
+    let mut sub_components = String::new();
+    if transitions.is_empty() {
+        let offset = format_offset(offset_seconds(tz, from));
+        sub_components.push_str(&format!(
+            "BEGIN:STANDARD\r\nDTSTART:19700101T000000\r\nTZOFFSETFROM:{offset}\r\nTZOFFSETTO:{offset}\r\nEND:STANDARD\r\n"
+        ));
+    } else {
+        // Observance for the offset in effect before the first transition,
+        // so times earlier than that switch still resolve.
+        let (_, initial_from, initial_to) = transitions[0];
+        let kind = if initial_to > initial_from { "STANDARD" } else { "DAYLIGHT" };
+        let offset = format_offset(initial_from);
+        sub_components.push_str(&format!(
+            "BEGIN:{kind}\r\nDTSTART:19700101T000000\r\nTZOFFSETFROM:{offset}\r\nTZOFFSETTO:{offset}\r\nEND:{kind}\r\n"
+        ));
+        for (instant, offset_from, offset_to) in transitions {
+            let kind = if offset_to > offset_from { "DAYLIGHT" } else { "STANDARD" };
+            let local_start = instant + Duration::seconds(i64::from(offset_to));
+            let dtstart = local_start.format("%Y%m%dT%H%M%S");
+            let offset_from = format_offset(offset_from);
+            let offset_to = format_offset(offset_to);
+            sub_components.push_str(&format!(
+                "BEGIN:{kind}\r\nDTSTART:{dtstart}\r\nTZOFFSETFROM:{offset_from}\r\nTZOFFSETTO:{offset_to}\r\nEND:{kind}\r\n"
+            ));
+        }
+    }
+
+    format!("BEGIN:VTIMEZONE\r\nTZID:{tz}\r\n{sub_components}END:VTIMEZONE\r\n")
+}
+
+/// Serializes `calendar`, inserting a `VTIMEZONE` for every distinct
+/// IANA timezone referenced by its events so clients can resolve them
+/// unambiguously instead of guessing. A `TZID` that is not an IANA name
+/// gets no `VTIMEZONE`.
+pub fn render(calendar: &Calendar, now: DateTime<Utc>) -> String {
+    let mut local_times_by_tz: HashMap<String, Vec<NaiveDateTime>> = HashMap::new();
+    for component in &calendar.components {
+        let CalendarComponent::Event(event) = component else {
+            continue;
+        };
+        for value in [event.get_start(), event.get_end()].into_iter().flatten() {
+            if let DatePerhapsTime::DateTime(CalendarDateTime::WithTimezone { date_time, tzid }) = value {
+                local_times_by_tz.entry(tzid).or_default().push(date_time);
+            }
+        }
+    }
+
+    let mut entries: Vec<_> = local_times_by_tz.into_iter().collect();
+    entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let vtimezones: String = entries.into_iter()
+        .filter_map(|(tzid, local_times)| {
+            let tz: Tz = tzid.parse().ok()?;
+            let instants: Vec<_> = local_times.iter()
+                .filter_map(|date_time| tz.from_local_datetime(date_time).single())
+                .map(|dt| dt.with_timezone(&Utc))
+                .collect();
+            Some(vtimezone_block(tz, &instants, now))
+        })
+        .collect();
+
+    let ics = calendar.to_string();
+    if vtimezones.is_empty() {
+        ics
+    } else {
+        ics.replacen("END:VCALENDAR", &format!("{vtimezones}END:VCALENDAR"), 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono_tz::Europe::Vienna;
+    use super::*;
+
+    #[test]
+    fn bisect_transition_finds_the_spring_forward_instant() {
+        let lo = Utc.with_ymd_and_hms(2026, 3, 29, 0, 0, 0).unwrap();
+        let hi = Utc.with_ymd_and_hms(2026, 3, 29, 3, 0, 0).unwrap();
+        let offset_at_lo = offset_seconds(Vienna, lo);
+
+        let transition = bisect_transition(Vienna, lo, hi, offset_at_lo);
+
+        let expected = Utc.with_ymd_and_hms(2026, 3, 29, 1, 0, 0).unwrap();
+        assert!((transition - expected).num_seconds().abs() <= 60);
+    }
+
+    #[test]
+    fn find_transitions_detects_the_spring_and_autumn_switches() {
+        let from = Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap();
+        let until = Utc.with_ymd_and_hms(2026, 12, 31, 0, 0, 0).unwrap();
+
+        let transitions = find_transitions(Vienna, from, until);
+
+        assert_eq!(transitions.len(), 2);
+        assert!(transitions[0].2 > transitions[0].1, "spring forward should increase the offset");
+        assert!(transitions[1].2 < transitions[1].1, "fall back should decrease the offset");
+    }
+
+    #[test]
+    fn find_transitions_is_empty_when_the_range_has_no_switch() {
+        let from = Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap();
+        let until = Utc.with_ymd_and_hms(2026, 1, 31, 0, 0, 0).unwrap();
+
+        assert!(find_transitions(Vienna, from, until).is_empty());
+    }
+
+    #[test]
+    fn vtimezone_block_emits_standard_and_daylight_around_a_transition() {
+        let now = Utc.with_ymd_and_hms(2026, 3, 29, 2, 0, 0).unwrap();
+
+        let block = vtimezone_block(Vienna, &[now], now);
+
+        assert!(block.contains("BEGIN:DAYLIGHT"));
+        assert!(block.contains("BEGIN:STANDARD"));
+        assert!(block.contains("TZOFFSETFROM:+0100"));
+        assert!(block.contains("TZOFFSETTO:+0200"));
+    }
+}
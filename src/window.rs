@@ -0,0 +1,117 @@
+use chrono::{DateTime, Duration, NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
+use icalendar::{CalendarComponent, CalendarDateTime, Component, DatePerhapsTime, EventLike};
+use crate::config::WindowConfig;
+
+fn to_utc(value: DatePerhapsTime) -> DateTime<Utc> {
+    match value {
+        DatePerhapsTime::DateTime(CalendarDateTime::Utc(dt)) => dt,
+        DatePerhapsTime::DateTime(CalendarDateTime::WithTimezone { date_time, tzid }) => {
+            tzid.parse::<Tz>().ok()
+                .and_then(|tz| tz.from_local_datetime(&date_time).single())
+                .map(|dt| dt.with_timezone(&Utc))
+                .unwrap_or_else(|| Utc.from_utc_datetime(&date_time))
+        },
+        DatePerhapsTime::DateTime(CalendarDateTime::Floating(date_time)) => {
+            Utc.from_utc_datetime(&date_time)
+        },
+        DatePerhapsTime::Date(date) => Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0).unwrap()),
+    }
+}
+
+fn to_date(value: &DatePerhapsTime) -> Option<NaiveDate> {
+    match value {
+        DatePerhapsTime::Date(date) => Some(*date),
+        DatePerhapsTime::DateTime(_) => None,
+    }
+}
+
+/// The `[now - down_days, now + up_days]` range an event's start/end must
+/// overlap to be kept, computed once per request so every event is
+/// compared against the same instant.
+pub struct TimeWindow {
+    from: DateTime<Utc>,
+    until: DateTime<Utc>,
+}
+
+impl TimeWindow {
+    pub fn new(now: DateTime<Utc>, config: &WindowConfig) -> Self {
+        Self {
+            from: now - Duration::days(config.down_days),
+            until: now + Duration::days(config.up_days),
+        }
+    }
+
+    pub fn from_date(&self) -> NaiveDate {
+        self.from.date_naive()
+    }
+
+    pub fn until_date(&self) -> NaiveDate {
+        self.until.date_naive()
+    }
+
+    /// Whether `component` falls at least partially inside the window.
+    /// All-day events are compared by date; timed events by instant.
+    /// Events with missing or unparseable start/end are kept.
+    pub fn retain(&self, component: &CalendarComponent) -> bool {
+        let CalendarComponent::Event(event) = component else {
+            return true;
+        };
+
+        let (Some(start), Some(end)) = (event.get_start(), event.get_end()) else {
+            return true;
+        };
+
+        if let (Some(start), Some(end)) = (to_date(&start), to_date(&end)) {
+            end >= self.from.date_naive() && start <= self.until.date_naive()
+        } else {
+            to_utc(end) >= self.from && to_utc(start) <= self.until
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use icalendar::Event;
+    use super::*;
+
+    fn window(now: DateTime<Utc>, down_days: i64, up_days: i64) -> TimeWindow {
+        TimeWindow::new(now, &WindowConfig { up_days, down_days })
+    }
+
+    fn timed_event(start: DateTime<Utc>, end: DateTime<Utc>) -> CalendarComponent {
+        CalendarComponent::Event(Event::new().starts(start).ends(end).done())
+    }
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 1, 10, 12, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn event_entirely_before_the_window_is_dropped() {
+        let window = window(now(), 7, 30);
+        let start = now() - Duration::days(8);
+        assert!(!window.retain(&timed_event(start, start + Duration::hours(1))));
+    }
+
+    #[test]
+    fn event_ending_exactly_at_the_window_start_is_kept() {
+        let window = window(now(), 7, 30);
+        let from = now() - Duration::days(7);
+        assert!(window.retain(&timed_event(from - Duration::hours(1), from)));
+    }
+
+    #[test]
+    fn event_starting_exactly_at_the_window_end_is_kept() {
+        let window = window(now(), 7, 30);
+        let until = now() + Duration::days(30);
+        assert!(window.retain(&timed_event(until, until + Duration::hours(1))));
+    }
+
+    #[test]
+    fn event_starting_just_after_the_window_end_is_dropped() {
+        let window = window(now(), 7, 30);
+        let start = now() + Duration::days(30) + Duration::seconds(1);
+        assert!(!window.retain(&timed_event(start, start + Duration::hours(1))));
+    }
+}